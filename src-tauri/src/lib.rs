@@ -1,9 +1,19 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Manager};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::Mutex as AsyncMutex;
 use uuid::Uuid;
 
+/// Longest-edge sizes (in pixels) generated for every stored image.
+const THUMBNAIL_SIZES: [u32; 2] = [128, 512];
+
+/// Current on-disk schema version for canvases and the tree. Bump this and add
+/// a migration step whenever the persisted shape changes.
+const CURRENT_VERSION: &str = "1.0.0";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct ViewBox {
     x: f64,
@@ -35,6 +45,8 @@ struct TreeNode {
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TreeStructure {
+    #[serde(default)]
+    version: String,
     #[serde(rename = "rootCanvases")]
     root_canvases: Vec<String>,
     canvases: std::collections::HashMap<String, TreeNode>,
@@ -49,6 +61,376 @@ struct UploadedFile {
     path: String,
 }
 
+/// Index entry tracking a single content-addressed image.
+///
+/// Images are stored under a name derived from their SHA-256 digest, so the
+/// same bytes uploaded into several canvases reuse one file on disk. The entry
+/// remembers every original name the bytes were uploaded under and how many
+/// canvas elements currently reference it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ImageIndexEntry {
+    filename: String,
+    #[serde(rename = "originalNames")]
+    original_names: Vec<String>,
+    size: u64,
+    #[serde(rename = "refCount")]
+    ref_count: u32,
+}
+
+type ImageIndex = std::collections::HashMap<String, ImageIndexEntry>;
+
+/// Decoded metadata for a stored image.
+///
+/// Computed once — from the file header, not a full decode — and cached to a
+/// per-image JSON record so repeated lookups are a cheap read rather than a
+/// re-decode, mirroring the thumbnail "generate once, reuse" pattern.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ImageDetails {
+    width: u32,
+    height: u32,
+    mime: String,
+    size: u64,
+    created: String,
+}
+
+/// User-settable preferences persisted to `storage/settings.json`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Settings {
+    #[serde(rename = "thumbnailParallelism")]
+    thumbnail_parallelism: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            thumbnail_parallelism: default_parallelism(),
+        }
+    }
+}
+
+fn default_parallelism() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// A pending thumbnail job: produce every variant of `hash` from `source`.
+struct ThumbJob {
+    images_dir: PathBuf,
+    hash: String,
+    source: PathBuf,
+}
+
+struct PoolState {
+    capacity: usize,
+    workers: usize,
+    queue: std::collections::VecDeque<ThumbJob>,
+}
+
+/// Bounded worker pool that turns originals into downscaled preview variants.
+///
+/// A fixed set of worker threads — at most `capacity` of them — drains a shared
+/// job queue, so importing dozens of images enqueues dozens of jobs but never
+/// spawns dozens of threads. The capacity can be changed at runtime: growing it
+/// spawns more workers, shrinking it lets idle workers exit. This keeps the
+/// canvas grid responsive while large boards import many images.
+struct Thumbnailer {
+    state: Arc<(Mutex<PoolState>, Condvar)>,
+}
+
+impl Thumbnailer {
+    fn new(parallelism: usize) -> Self {
+        let thumbnailer = Thumbnailer {
+            state: Arc::new((
+                Mutex::new(PoolState {
+                    capacity: parallelism.max(1),
+                    workers: 0,
+                    queue: std::collections::VecDeque::new(),
+                }),
+                Condvar::new(),
+            )),
+        };
+        thumbnailer.spawn_workers();
+        thumbnailer
+    }
+
+    /// Bring the live worker count up to `capacity`, spawning threads as needed.
+    fn spawn_workers(&self) {
+        let (lock, _) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        while state.workers < state.capacity {
+            state.workers += 1;
+            let shared = Arc::clone(&self.state);
+            std::thread::spawn(move || Thumbnailer::worker_loop(shared));
+        }
+    }
+
+    /// Drain the shared queue until the pool is shrunk below this worker's slot.
+    fn worker_loop(state: Arc<(Mutex<PoolState>, Condvar)>) {
+        let (lock, cvar) = &*state;
+        loop {
+            let job = {
+                let mut guard = lock.lock().unwrap();
+                loop {
+                    // Exit if capacity was lowered below the current worker count.
+                    if guard.workers > guard.capacity {
+                        guard.workers -= 1;
+                        cvar.notify_all();
+                        return;
+                    }
+                    if let Some(job) = guard.queue.pop_front() {
+                        break job;
+                    }
+                    guard = cvar.wait(guard).unwrap();
+                }
+            };
+
+            for size in THUMBNAIL_SIZES {
+                let dest = thumbnail_path(&job.images_dir, &job.hash, size);
+                if dest.exists() {
+                    continue;
+                }
+                if let Err(e) = generate_thumbnail(&job.source, &dest, size) {
+                    log::warn!("Failed to generate {}px thumbnail for {}: {}", size, job.hash, e);
+                }
+            }
+        }
+    }
+
+    /// Resize the pool. Growing spawns new workers immediately; shrinking lets
+    /// surplus workers exit once they finish their current job.
+    fn set_parallelism(&self, parallelism: usize) {
+        {
+            let (lock, _) = &*self.state;
+            let mut state = lock.lock().unwrap();
+            state.capacity = parallelism.max(1);
+        }
+        self.spawn_workers();
+        let (_, cvar) = &*self.state;
+        cvar.notify_all();
+    }
+
+    /// Queue thumbnail generation for `hash`; a pooled worker picks it up.
+    fn enqueue(&self, images_dir: PathBuf, hash: String, source: PathBuf) {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        state.queue.push_back(ThumbJob { images_dir, hash, source });
+        cvar.notify_one();
+    }
+}
+
+/// Serializes the image-index read-modify-write. `save_image`/`delete_image`
+/// are genuinely concurrent async handlers; without this, two uploads could
+/// both read the index, each insert their entry, and the second write clobber
+/// the first — losing a dedup entry or a ref-count increment.
+#[derive(Default)]
+struct ImageIndexLock {
+    lock: AsyncMutex<()>,
+}
+
+/// Per-canvas write locks, so concurrent `update_canvas` calls to the same
+/// board serialize instead of interleaving and corrupting the JSON file.
+/// Reads and writes to distinct canvases stay fully concurrent.
+#[derive(Default)]
+struct CanvasLocks {
+    locks: std::sync::Mutex<std::collections::HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+impl CanvasLocks {
+    fn for_canvas(&self, id: &str) -> Arc<AsyncMutex<()>> {
+        let mut locks = self.locks.lock().unwrap();
+        // Drop entries no in-flight writer still holds (strong count of 1 means
+        // only the map references it), so the map doesn't retain a lock per
+        // board ever edited over a long-running session.
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(id.to_string()).or_default().clone()
+    }
+
+    /// Forget a canvas's lock once it no longer exists.
+    fn forget(&self, id: &str) {
+        self.locks.lock().unwrap().remove(id);
+    }
+}
+
+/// Write `bytes` to `path` crash-safely: stream them to a sibling temp file,
+/// flush and fsync, then atomically rename it over the target. Readers always
+/// observe either the complete old file or the complete new one, never a
+/// half-written canvas.
+async fn atomic_write(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    use tokio::io::AsyncWriteExt;
+
+    let file_name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| "Invalid destination path".to_string())?;
+    let tmp_path = match path.parent() {
+        Some(parent) => parent.join(format!("{}.tmp-{}", file_name, Uuid::new_v4())),
+        None => PathBuf::from(format!("{}.tmp-{}", file_name, Uuid::new_v4())),
+    };
+
+    let mut file = tokio::fs::File::create(&tmp_path)
+        .await
+        .map_err(|e| format!("Failed to create temp file: {}", e))?;
+    file.write_all(bytes)
+        .await
+        .map_err(|e| format!("Failed to write temp file: {}", e))?;
+    file.flush()
+        .await
+        .map_err(|e| format!("Failed to flush temp file: {}", e))?;
+    file.sync_all()
+        .await
+        .map_err(|e| format!("Failed to sync temp file: {}", e))?;
+    drop(file);
+
+    tokio::fs::rename(&tmp_path, path)
+        .await
+        .map_err(|e| format!("Failed to finalize write: {}", e))
+}
+
+/// Copy the current file to a `.bak` sibling before it is overwritten, so a
+/// corrupted board can be rolled back to the previous version.
+async fn backup_existing(path: &Path) -> Result<(), String> {
+    if path.exists() {
+        let backup = path.with_extension("json.bak");
+        tokio::fs::copy(path, &backup)
+            .await
+            .map_err(|e| format!("Failed to back up file: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Age past which a leftover `*.tmp-*` file is treated as abandoned by a
+/// crashed writer rather than in flight. `atomic_write` creates its temp file
+/// and renames it in well under this window, so the sweep never deletes one a
+/// concurrent writer is still between create and rename on.
+const STALE_TEMP_AGE: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// Remove stray `*.tmp-*` files left behind by an interrupted write. Only files
+/// older than [`STALE_TEMP_AGE`] are touched, so sweeping on every read can't
+/// race another board's in-flight `atomic_write` and make its rename fail.
+fn clean_temp_files(dir: &Path) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        if let Some(name) = entry.file_name().to_str() {
+            if name.contains(".tmp-") {
+                let stale = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.elapsed().ok())
+                    .map(|age| age >= STALE_TEMP_AGE)
+                    .unwrap_or(false);
+                if stale {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+    }
+}
+
+fn thumbnail_path(images_dir: &Path, hash: &str, size: u32) -> PathBuf {
+    images_dir.join("thumbs").join(format!("{}_{}.png", hash, size))
+}
+
+/// Decode `source`, scale it so its longest edge is `size`, and write a PNG
+/// preview to `dest`, creating the `thumbs` directory as needed.
+///
+/// PNG is used rather than WebP because the `image` crate's WebP *encoder* is
+/// feature-gated; PNG support ships in its default features, so a thumbnail job
+/// can never silently fail on a build where the WebP encoder isn't compiled in.
+fn generate_thumbnail(source: &Path, dest: &Path, size: u32) -> Result<(), String> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create thumbs directory: {}", e))?;
+    }
+
+    let img = image::open(source)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+
+    // `thumbnail` preserves aspect ratio and bounds the longest edge.
+    let thumb = img.thumbnail(size, size);
+    thumb
+        .save_with_format(dest, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to write thumbnail: {}", e))
+}
+
+fn details_path(images_dir: &Path, filename: &str) -> PathBuf {
+    images_dir.join("details").join(format!("{}.json", filename))
+}
+
+/// Best-effort MIME type for a stored image. Sniffs the file's magic bytes
+/// first, so a mis-/un-named upload (PNG bytes stored as `photo.dat`) is still
+/// classified correctly, and falls back to the file extension for formats the
+/// `image` crate can't guess from content, such as SVG.
+fn sniff_mime(source: &Path) -> String {
+    if let Ok(bytes) = fs::read(source) {
+        if let Ok(format) = image::guess_format(&bytes) {
+            return format.to_mime_type().to_string();
+        }
+    }
+
+    match source
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("bmp") => "image/bmp",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+    .to_string()
+}
+
+/// Read an image's dimensions from its header and assemble its details record.
+fn compute_image_details(source: &Path, created: String) -> Result<ImageDetails, String> {
+    let mime = sniff_mime(source);
+    let size = fs::metadata(source)
+        .map_err(|e| format!("Failed to stat image: {}", e))?
+        .len();
+
+    // SVG is vector and unsupported by the `image` crate, and a scalable image
+    // has no intrinsic pixel size. Report zero dimensions rather than failing
+    // the decode for a format `sniff_mime` otherwise recognises.
+    let (width, height) = if mime == "image/svg+xml" {
+        (0, 0)
+    } else {
+        image::image_dimensions(source)
+            .map_err(|e| format!("Failed to read image dimensions: {}", e))?
+    };
+
+    Ok(ImageDetails {
+        width,
+        height,
+        mime,
+        size,
+        created,
+    })
+}
+
+fn write_image_details(
+    images_dir: &Path,
+    filename: &str,
+    details: &ImageDetails,
+) -> Result<(), String> {
+    let path = details_path(images_dir, filename);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create details directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(details)
+        .map_err(|e| format!("Failed to serialize image details: {}", e))?;
+    fs::write(&path, content)
+        .map_err(|e| format!("Failed to save image details: {}", e))
+}
+
 // Helper function to get storage paths
 fn get_storage_dir(app: &AppHandle) -> Result<PathBuf, String> {
     app.path()
@@ -72,6 +454,58 @@ fn get_images_dir(app: &AppHandle) -> Result<PathBuf, String> {
     Ok(path)
 }
 
+fn get_image_index_file(app: &AppHandle) -> Result<PathBuf, String> {
+    let mut path = get_images_dir(app)?;
+    path.push("index.json");
+    Ok(path)
+}
+
+fn read_image_index(app: &AppHandle) -> Result<ImageIndex, String> {
+    let index_file = get_image_index_file(app)?;
+    if !index_file.exists() {
+        return Ok(ImageIndex::new());
+    }
+
+    let content = fs::read_to_string(&index_file)
+        .map_err(|e| format!("Failed to read image index: {}", e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse image index: {}", e))
+}
+
+fn write_image_index(app: &AppHandle, index: &ImageIndex) -> Result<(), String> {
+    let index_file = get_image_index_file(app)?;
+    let content = serde_json::to_string_pretty(index)
+        .map_err(|e| format!("Failed to serialize image index: {}", e))?;
+    fs::write(&index_file, content)
+        .map_err(|e| format!("Failed to save image index: {}", e))
+}
+
+fn get_settings_file(app: &AppHandle) -> Result<PathBuf, String> {
+    let mut path = get_storage_dir(app)?;
+    path.push("settings.json");
+    Ok(path)
+}
+
+fn read_settings(app: &AppHandle) -> Result<Settings, String> {
+    let settings_file = get_settings_file(app)?;
+    if !settings_file.exists() {
+        return Ok(Settings::default());
+    }
+
+    let content = fs::read_to_string(&settings_file)
+        .map_err(|e| format!("Failed to read settings: {}", e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse settings: {}", e))
+}
+
+fn write_settings(app: &AppHandle, settings: &Settings) -> Result<(), String> {
+    let settings_file = get_settings_file(app)?;
+    let content = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    fs::write(&settings_file, content)
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
 fn get_tree_file(app: &AppHandle) -> Result<PathBuf, String> {
     let mut path = get_storage_dir(app)?;
     path.push("tree.json");
@@ -90,38 +524,120 @@ fn ensure_storage_directories(app: &AppHandle) -> Result<(), String> {
     Ok(())
 }
 
+/// A single schema upgrade, keyed by the `version` string it upgrades *from*.
+/// `apply` takes the raw JSON and returns the upgraded JSON plus the version it
+/// produced; the driver repeats until the value reaches [`CURRENT_VERSION`].
+struct MigrationStep {
+    from: &'static str,
+    apply: fn(serde_json::Value) -> Result<(serde_json::Value, String), String>,
+}
+
+/// Run every applicable migration step against `value` in sequence. Returns the
+/// upgraded value and whether anything changed (so callers can skip rewriting
+/// already-current files).
+fn migrate(
+    mut value: serde_json::Value,
+    steps: &[MigrationStep],
+) -> Result<(serde_json::Value, bool), String> {
+    let mut changed = false;
+
+    loop {
+        let version = value
+            .get("version")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        if version == CURRENT_VERSION {
+            break;
+        }
+
+        let step = steps
+            .iter()
+            .find(|s| s.from == version)
+            .ok_or_else(|| format!("No migration step from version '{}'", version))?;
+
+        let (mut next, new_version) = (step.apply)(value)?;
+        if let Some(obj) = next.as_object_mut() {
+            obj.insert("version".to_string(), serde_json::Value::String(new_version));
+        }
+        value = next;
+        changed = true;
+    }
+
+    Ok((value, changed))
+}
+
+/// Canvas upgrades, ordered by the version they upgrade from.
+const CANVAS_MIGRATIONS: &[MigrationStep] = &[MigrationStep {
+    from: "",
+    apply: migrate_canvas_unversioned,
+}];
+
+/// Tree upgrades, ordered by the version they upgrade from.
+const TREE_MIGRATIONS: &[MigrationStep] = &[MigrationStep {
+    from: "",
+    apply: migrate_tree_unversioned,
+}];
+
+/// Stamp an initial version and refresh `modified` on pre-versioning canvases.
+fn migrate_canvas_unversioned(
+    mut value: serde_json::Value,
+) -> Result<(serde_json::Value, String), String> {
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert(
+            "modified".to_string(),
+            serde_json::Value::String(chrono::Utc::now().to_rfc3339()),
+        );
+    }
+    Ok((value, "1.0.0".to_string()))
+}
+
+/// Stamp an initial version on pre-versioning trees.
+fn migrate_tree_unversioned(
+    value: serde_json::Value,
+) -> Result<(serde_json::Value, String), String> {
+    Ok((value, "1.0.0".to_string()))
+}
+
 #[tauri::command]
-fn get_canvas(app: AppHandle, id: String) -> Result<Canvas, String> {
-    let mut canvas_path = get_canvases_dir(&app)?;
+async fn get_canvas(app: AppHandle, id: String) -> Result<Canvas, String> {
+    let canvases_dir = get_canvases_dir(&app)?;
+
+    // Sweep up any temp files left by an interrupted write.
+    clean_temp_files(&canvases_dir);
+
+    let mut canvas_path = canvases_dir;
     canvas_path.push(format!("{}.json", id));
 
-    let content = fs::read_to_string(&canvas_path)
+    let content = tokio::fs::read_to_string(&canvas_path)
+        .await
         .map_err(|_| "Canvas not found".to_string())?;
 
-    let mut canvas: Canvas = serde_json::from_str(&content)
+    let raw: serde_json::Value = serde_json::from_str(&content)
         .map_err(|e| format!("Failed to parse canvas: {}", e))?;
 
-    // Migration: Add version if missing
-    if canvas.version.is_empty() {
-        canvas.version = "1.0.0".to_string();
-        canvas.modified = chrono::Utc::now().to_rfc3339();
+    let (migrated, changed) = migrate(raw, CANVAS_MIGRATIONS)?;
 
-        // Save the migrated canvas
+    let canvas: Canvas = serde_json::from_value(migrated)
+        .map_err(|e| format!("Failed to parse canvas: {}", e))?;
+
+    // Persist the upgraded file so the migration only runs once.
+    if changed {
         let content = serde_json::to_string_pretty(&canvas)
             .map_err(|e| format!("Failed to serialize canvas: {}", e))?;
-        fs::write(&canvas_path, content)
-            .map_err(|e| format!("Failed to save canvas: {}", e))?;
+        atomic_write(&canvas_path, content.as_bytes()).await?;
     }
 
     Ok(canvas)
 }
 
 #[tauri::command]
-fn create_canvas(app: AppHandle, name: Option<String>, parent_id: Option<String>) -> Result<Canvas, String> {
+async fn create_canvas(app: AppHandle, name: Option<String>, parent_id: Option<String>) -> Result<Canvas, String> {
     ensure_storage_directories(&app)?;
 
     let canvas = Canvas {
-        version: "1.0.0".to_string(),
+        version: CURRENT_VERSION.to_string(),
         id: Uuid::new_v4().to_string(),
         name: name.unwrap_or_else(|| "New Canvas".to_string()),
         parent_id,
@@ -141,14 +657,18 @@ fn create_canvas(app: AppHandle, name: Option<String>, parent_id: Option<String>
 
     let content = serde_json::to_string_pretty(&canvas)
         .map_err(|e| format!("Failed to serialize canvas: {}", e))?;
-    fs::write(&canvas_path, content)
-        .map_err(|e| format!("Failed to save canvas: {}", e))?;
+    atomic_write(&canvas_path, content.as_bytes()).await?;
 
     Ok(canvas)
 }
 
 #[tauri::command]
-fn update_canvas(app: AppHandle, id: String, canvas_data: serde_json::Value) -> Result<Canvas, String> {
+async fn update_canvas(
+    app: AppHandle,
+    locks: State<'_, CanvasLocks>,
+    id: String,
+    canvas_data: serde_json::Value,
+) -> Result<Canvas, String> {
     let mut canvas_path = get_canvases_dir(&app)?;
     canvas_path.push(format!("{}.json", id));
 
@@ -157,36 +677,55 @@ fn update_canvas(app: AppHandle, id: String, canvas_data: serde_json::Value) ->
 
     canvas.modified = chrono::Utc::now().to_rfc3339();
     if canvas.version.is_empty() {
-        canvas.version = "1.0.0".to_string();
+        canvas.version = CURRENT_VERSION.to_string();
     }
 
     let content = serde_json::to_string_pretty(&canvas)
         .map_err(|e| format!("Failed to serialize canvas: {}", e))?;
-    fs::write(&canvas_path, content)
-        .map_err(|e| format!("Failed to save canvas: {}", e))?;
+
+    // Serialize writes to this board so two saves can't interleave.
+    let lock = locks.for_canvas(&id);
+    let _guard = lock.lock().await;
+
+    backup_existing(&canvas_path).await?;
+    atomic_write(&canvas_path, content.as_bytes()).await?;
 
     Ok(canvas)
 }
 
 #[tauri::command]
-fn delete_canvas(app: AppHandle, id: String) -> Result<bool, String> {
+async fn delete_canvas(
+    app: AppHandle,
+    locks: State<'_, CanvasLocks>,
+    id: String,
+) -> Result<bool, String> {
     let mut canvas_path = get_canvases_dir(&app)?;
     canvas_path.push(format!("{}.json", id));
 
-    fs::remove_file(&canvas_path)
+    tokio::fs::remove_file(&canvas_path)
+        .await
         .map_err(|_| "Canvas not found".to_string())?;
 
+    // Release the board's write lock so the map doesn't retain it forever.
+    locks.forget(&id);
+
     Ok(true)
 }
 
 #[tauri::command]
-fn get_tree(app: AppHandle) -> Result<TreeStructure, String> {
+async fn get_tree(app: AppHandle) -> Result<TreeStructure, String> {
     let tree_file = get_tree_file(&app)?;
 
+    // Sweep up any temp files left by an interrupted write.
+    if let Some(parent) = tree_file.parent() {
+        clean_temp_files(parent);
+    }
+
     if !tree_file.exists() {
         // Initialize default tree structure
         ensure_storage_directories(&app)?;
         let default_tree = TreeStructure {
+            version: CURRENT_VERSION.to_string(),
             root_canvases: vec!["main".to_string()],
             canvases: {
                 let mut map = std::collections::HashMap::new();
@@ -201,57 +740,195 @@ fn get_tree(app: AppHandle) -> Result<TreeStructure, String> {
 
         let content = serde_json::to_string_pretty(&default_tree)
             .map_err(|e| format!("Failed to serialize tree: {}", e))?;
-        fs::write(&tree_file, content)
-            .map_err(|e| format!("Failed to save tree: {}", e))?;
+        atomic_write(&tree_file, content.as_bytes()).await?;
 
         return Ok(default_tree);
     }
 
-    let content = fs::read_to_string(&tree_file)
+    let content = tokio::fs::read_to_string(&tree_file)
+        .await
         .map_err(|e| format!("Failed to read tree file: {}", e))?;
 
-    serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse tree: {}", e))
+    let raw: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse tree: {}", e))?;
+
+    let (migrated, changed) = migrate(raw, TREE_MIGRATIONS)?;
+
+    let tree: TreeStructure = serde_json::from_value(migrated)
+        .map_err(|e| format!("Failed to parse tree: {}", e))?;
+
+    if changed {
+        let content = serde_json::to_string_pretty(&tree)
+            .map_err(|e| format!("Failed to serialize tree: {}", e))?;
+        atomic_write(&tree_file, content.as_bytes()).await?;
+    }
+
+    Ok(tree)
 }
 
 #[tauri::command]
-fn update_tree(app: AppHandle, tree_data: TreeStructure) -> Result<TreeStructure, String> {
+async fn update_tree(app: AppHandle, tree_data: TreeStructure) -> Result<TreeStructure, String> {
     let tree_file = get_tree_file(&app)?;
 
     let content = serde_json::to_string_pretty(&tree_data)
         .map_err(|e| format!("Failed to serialize tree: {}", e))?;
-    fs::write(&tree_file, content)
-        .map_err(|e| format!("Failed to save tree: {}", e))?;
+    backup_existing(&tree_file).await?;
+    atomic_write(&tree_file, content.as_bytes()).await?;
 
     Ok(tree_data)
 }
 
 #[tauri::command]
-fn save_image(app: AppHandle, filename: String, data: Vec<u8>) -> Result<UploadedFile, String> {
-    ensure_storage_directories(&app)?;
+async fn save_image(
+    app: AppHandle,
+    thumbnailer: State<'_, Thumbnailer>,
+    index_lock: State<'_, ImageIndexLock>,
+    filename: String,
+    data: Vec<u8>,
+) -> Result<UploadedFile, String> {
+    let images_dir = get_images_dir(&app)?;
 
-    let mut image_path = get_images_dir(&app)?;
+    // Create the storage directories without blocking the async runtime.
+    tokio::fs::create_dir_all(&images_dir)
+        .await
+        .map_err(|e| format!("Failed to create images directory: {}", e))?;
 
-    // Generate UUID filename with extension
-    let ext = std::path::Path::new(&filename)
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("png");
+    // Derive a content-addressed filename from the SHA-256 digest so identical
+    // bytes map to one file on disk regardless of how many canvases use them.
+    let hash = format!("{:x}", Sha256::digest(&data));
+    let size = data.len() as u64;
+
+    // Resolve the stored filename and reserve the index entry in a single
+    // critical section. Doing the lookup and the insert under one lock keeps two
+    // concurrent first-time uploads of identical bytes from each deriving a
+    // distinct filename (e.g. `<hash>.png` vs `<hash>.jpg`) and orphaning one of
+    // the written files. On a dedup hit the filename comes from the existing
+    // entry, so its extension wins and no second copy is written. The index's
+    // synchronous read-modify-write runs on a blocking thread so it doesn't
+    // stall the runtime while the lock is held.
+    let new_filename = {
+        let _index_guard = index_lock.lock.lock().await;
+        let app = app.clone();
+        let filename = filename.clone();
+        let hash = hash.clone();
+        tokio::task::spawn_blocking(move || -> Result<String, String> {
+            let mut index = read_image_index(&app)?;
+            let new_filename = match index.get(&hash) {
+                Some(entry) => entry.filename.clone(),
+                None => {
+                    let ext = std::path::Path::new(&filename)
+                        .extension()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or("png");
+                    format!("{}.{}", hash, ext)
+                }
+            };
+            let entry = index.entry(hash.clone()).or_insert_with(|| ImageIndexEntry {
+                filename: new_filename.clone(),
+                original_names: Vec::new(),
+                size,
+                ref_count: 0,
+            });
+            if !entry.original_names.contains(&filename) {
+                entry.original_names.push(filename.clone());
+            }
+            entry.ref_count += 1;
+            write_image_index(&app, &index)?;
+            Ok(new_filename)
+        })
+        .await
+        .map_err(|e| format!("Failed to update image index: {}", e))??
+    };
+
+    let image_path = images_dir.join(&new_filename);
+
+    // Write the bytes and compute details outside the index lock so uploads of
+    // different images don't serialize on it. Only touch the disk the first
+    // time we see these bytes.
+    if !image_path.exists() {
+        atomic_write(&image_path, &data).await?;
 
-    let new_filename = format!("{}.{}", Uuid::new_v4(), ext);
-    image_path.push(&new_filename);
+        // Record details up front so later lookups never re-decode. The header
+        // decode and its synchronous writes run on a blocking thread.
+        let images_dir = images_dir.clone();
+        let image_path = image_path.clone();
+        let details_filename = new_filename.clone();
+        tokio::task::spawn_blocking(move || -> Result<(), String> {
+            if let Ok(details) =
+                compute_image_details(&image_path, chrono::Utc::now().to_rfc3339())
+            {
+                write_image_details(&images_dir, &details_filename, &details)?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|e| format!("Failed to write image details: {}", e))??;
+    }
 
-    fs::write(&image_path, &data)
-        .map_err(|e| format!("Failed to save image: {}", e))?;
+    // Kick off preview generation in the background so the upload returns
+    // immediately; the grid can request the tiny previews as they land.
+    thumbnailer.enqueue(images_dir, hash, image_path);
 
     Ok(UploadedFile {
         filename: new_filename.clone(),
         original_name: filename,
-        size: data.len() as u64,
+        size,
         path: format!("/api/images/{}", new_filename),
     })
 }
 
+#[tauri::command]
+async fn delete_image(
+    app: AppHandle,
+    index_lock: State<'_, ImageIndexLock>,
+    hash: String,
+) -> Result<bool, String> {
+    let _index_guard = index_lock.lock.lock().await;
+
+    let mut index = read_image_index(&app)?;
+
+    let entry = match index.get_mut(&hash) {
+        Some(entry) => entry,
+        None => return Err("Image not found".to_string()),
+    };
+
+    entry.ref_count = entry.ref_count.saturating_sub(1);
+
+    // Only remove the file once nothing references it anymore, so deleting an
+    // element from one canvas never orphans an image still used elsewhere.
+    let removed = if entry.ref_count == 0 {
+        let filename = entry.filename.clone();
+        let images_dir = get_images_dir(&app)?;
+
+        let image_path = images_dir.join(&filename);
+        if image_path.exists() {
+            fs::remove_file(&image_path)
+                .map_err(|e| format!("Failed to remove image: {}", e))?;
+        }
+
+        // Drop the generated artifacts too so nothing is orphaned on disk.
+        for size in THUMBNAIL_SIZES {
+            let thumb = thumbnail_path(&images_dir, &hash, size);
+            if thumb.exists() {
+                let _ = fs::remove_file(&thumb);
+            }
+        }
+        let details = details_path(&images_dir, &filename);
+        if details.exists() {
+            let _ = fs::remove_file(&details);
+        }
+
+        index.remove(&hash);
+        true
+    } else {
+        false
+    };
+
+    write_image_index(&app, &index)?;
+
+    Ok(removed)
+}
+
 #[tauri::command]
 fn get_image_path(app: AppHandle, filename: String) -> Result<String, String> {
     let mut image_path = get_images_dir(&app)?;
@@ -266,13 +943,80 @@ fn get_image_path(app: AppHandle, filename: String) -> Result<String, String> {
         .map(|s| s.to_string())
 }
 
+#[tauri::command]
+fn get_image_details(app: AppHandle, filename: String) -> Result<ImageDetails, String> {
+    let images_dir = get_images_dir(&app)?;
+    let record_path = details_path(&images_dir, &filename);
+
+    // Cached record: a plain JSON read.
+    if record_path.exists() {
+        let content = fs::read_to_string(&record_path)
+            .map_err(|e| format!("Failed to read image details: {}", e))?;
+        return serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse image details: {}", e));
+    }
+
+    // Regenerate lazily if the details file is missing.
+    let source = images_dir.join(&filename);
+    if !source.exists() {
+        return Err("Image not found".to_string());
+    }
+
+    let created = fs::metadata(&source)
+        .ok()
+        .and_then(|m| m.created().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .and_then(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, d.subsec_nanos()))
+        .map(|dt| dt.to_rfc3339())
+        .unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+
+    let details = compute_image_details(&source, created)?;
+    write_image_details(&images_dir, &filename, &details)?;
+
+    Ok(details)
+}
+
+#[tauri::command]
+fn get_thumbnail_path(app: AppHandle, hash: String, size: u32) -> Result<String, String> {
+    let images_dir = get_images_dir(&app)?;
+    let thumb_path = thumbnail_path(&images_dir, &hash, size);
+
+    // Generate on demand if the cached variant isn't there yet.
+    if !thumb_path.exists() {
+        let entry = read_image_index(&app)?
+            .remove(&hash)
+            .ok_or_else(|| "Image not found".to_string())?;
+        let source = images_dir.join(&entry.filename);
+        generate_thumbnail(&source, &thumb_path, size)?;
+    }
+
+    thumb_path.to_str()
+        .ok_or_else(|| "Invalid path".to_string())
+        .map(|s| s.to_string())
+}
+
+#[tauri::command]
+fn set_thumbnail_parallelism(
+    app: AppHandle,
+    thumbnailer: State<'_, Thumbnailer>,
+    n: usize,
+) -> Result<(), String> {
+    let mut settings = read_settings(&app)?;
+    settings.thumbnail_parallelism = n.max(1);
+    write_settings(&app, &settings)?;
+
+    thumbnailer.set_parallelism(settings.thumbnail_parallelism);
+
+    Ok(())
+}
+
 fn init_default_canvas(app: &AppHandle) -> Result<(), String> {
     let mut canvas_path = get_canvases_dir(app)?;
     canvas_path.push("main.json");
 
     if !canvas_path.exists() {
         let default_canvas = Canvas {
-            version: "1.0.0".to_string(),
+            version: CURRENT_VERSION.to_string(),
             id: "main".to_string(),
             name: "Main Canvas".to_string(),
             parent_id: None,
@@ -322,6 +1066,14 @@ pub fn run() {
             ensure_storage_directories(&app.handle())?;
             init_default_canvas(&app.handle())?;
 
+            // Size the thumbnail pool from the persisted preference.
+            let parallelism = read_settings(&app.handle())
+                .map(|s| s.thumbnail_parallelism)
+                .unwrap_or_else(|_| default_parallelism());
+            app.manage(Thumbnailer::new(parallelism));
+            app.manage(CanvasLocks::default());
+            app.manage(ImageIndexLock::default());
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -332,8 +1084,56 @@ pub fn run() {
             get_tree,
             update_tree,
             save_image,
-            get_image_path
+            delete_image,
+            get_image_path,
+            get_image_details,
+            get_thumbnail_path,
+            set_thumbnail_parallelism
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canvas_unversioned_step_stamps_version_and_refreshes_modified() {
+        let input = serde_json::json!({ "modified": "old" });
+        let (value, version) = migrate_canvas_unversioned(input).unwrap();
+        assert_eq!(version, "1.0.0");
+        assert_ne!(value["modified"], serde_json::json!("old"));
+    }
+
+    #[test]
+    fn tree_unversioned_step_stamps_version_and_preserves_payload() {
+        let input = serde_json::json!({ "rootCanvases": ["main"] });
+        let (value, version) = migrate_tree_unversioned(input).unwrap();
+        assert_eq!(version, "1.0.0");
+        assert_eq!(value["rootCanvases"], serde_json::json!(["main"]));
+    }
+
+    #[test]
+    fn migrate_upgrades_unversioned_value_to_current() {
+        let input = serde_json::json!({ "name": "board" });
+        let (value, changed) = migrate(input, CANVAS_MIGRATIONS).unwrap();
+        assert!(changed);
+        assert_eq!(value["version"], serde_json::json!(CURRENT_VERSION));
+    }
+
+    #[test]
+    fn migrate_is_a_no_op_when_already_current() {
+        let input = serde_json::json!({ "version": CURRENT_VERSION, "name": "board" });
+        let (value, changed) = migrate(input, CANVAS_MIGRATIONS).unwrap();
+        assert!(!changed);
+        assert_eq!(value["version"], serde_json::json!(CURRENT_VERSION));
+    }
+
+    #[test]
+    fn migrate_errors_on_unknown_version() {
+        let input = serde_json::json!({ "version": "9.9.9" });
+        let err = migrate(input, CANVAS_MIGRATIONS).unwrap_err();
+        assert!(err.contains("9.9.9"));
+    }
+}